@@ -0,0 +1,307 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A per-blob zstd compression wrapper blobstore. Unlike `PackBlob`, which
+//! groups related blobs together, `CompressedBlob` compresses each blob
+//! independently, optionally against a shared trained dictionary, so it can
+//! be used standalone for storage savings with negligible overhead on small
+//! objects.
+
+#![deny(warnings)]
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+
+use anyhow::Context;
+use anyhow::Error;
+use async_trait::async_trait;
+use blobstore::Blobstore;
+use blobstore::BlobstoreGetData;
+use blobstore::BlobstorePutOps;
+use blobstore::OverwriteStatus;
+use blobstore::PutBehaviour;
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+
+/// Codec tag prefixed to every stored blob.
+const CODEC_STORED: u8 = 0;
+const CODEC_ZSTD: u8 = 1;
+const CODEC_ZSTD_DICT: u8 = 2;
+
+#[derive(Clone, Debug)]
+pub struct CompressionOptions {
+    /// zstd compression level to use for new puts.
+    pub level: i32,
+    /// Minimum payload size, in bytes, before compression is attempted.
+    /// Smaller blobs are stored as-is to avoid per-blob overhead.
+    pub min_size: usize,
+    /// The dictionary used to compress new `CODEC_ZSTD_DICT` puts. Its id is
+    /// persisted alongside each blob (see `encode`/`decode`) so that a later
+    /// rotation to a new dictionary doesn't orphan blobs written against
+    /// this one - they remain decodable as long as this id is still present
+    /// in `dictionary_table`.
+    pub dictionary: Option<(u32, Vec<u8>)>,
+    /// Every dictionary (including retired ones) that `decode` may need to
+    /// look up by id. Should always include `dictionary`'s id if set; keep
+    /// retired entries around for as long as blobs compressed against them
+    /// may still be read.
+    pub dictionary_table: HashMap<u32, Vec<u8>>,
+}
+
+impl Default for CompressionOptions {
+    fn default() -> Self {
+        Self {
+            level: 3,
+            min_size: 256,
+            dictionary: None,
+            dictionary_table: HashMap::new(),
+        }
+    }
+}
+
+/// A `Blobstore`/`BlobstorePutOps` wrapper that compresses payloads with
+/// zstd above a configurable size threshold and stores smaller blobs
+/// uncompressed behind a `CODEC_STORED` tag. Every stored blob carries a
+/// codec tag; `get` expects one, so blobs stored without it are not passed
+/// through as-is.
+#[derive(Clone)]
+pub struct CompressedBlob<T> {
+    inner: T,
+    options: CompressionOptions,
+}
+
+impl<T: fmt::Debug> fmt::Debug for CompressedBlob<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedBlob")
+            .field("inner", &self.inner)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for CompressedBlob<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CompressedBlob<{}>", self.inner)
+    }
+}
+
+impl<T> CompressedBlob<T> {
+    pub fn new(inner: T, options: CompressionOptions) -> Self {
+        Self { inner, options }
+    }
+
+    fn encode(&self, raw: &[u8]) -> Result<Vec<u8>, Error> {
+        if raw.len() < self.options.min_size {
+            let mut out = Vec::with_capacity(raw.len() + 1);
+            out.push(CODEC_STORED);
+            out.extend_from_slice(raw);
+            return Ok(out);
+        }
+
+        let (tag, dict_id, compressed) = match &self.options.dictionary {
+            Some((dict_id, dict)) => {
+                let mut encoder =
+                    zstd::bulk::Compressor::with_dictionary(self.options.level, dict)
+                        .context("failed to initialize zstd dictionary compressor")?;
+                (CODEC_ZSTD_DICT, Some(*dict_id), encoder.compress(raw)?)
+            }
+            None => (
+                CODEC_ZSTD,
+                None,
+                zstd::bulk::compress(raw, self.options.level)?,
+            ),
+        };
+
+        let mut out = Vec::with_capacity(compressed.len() + 13);
+        out.push(tag);
+        if let Some(dict_id) = dict_id {
+            out.extend_from_slice(&dict_id.to_be_bytes());
+        }
+        out.extend_from_slice(&(raw.len() as u64).to_be_bytes());
+        out.extend_from_slice(&compressed);
+        Ok(out)
+    }
+
+    fn decode(&self, encoded: &[u8]) -> Result<Vec<u8>, Error> {
+        let (&tag, rest) = encoded
+            .split_first()
+            .context("compressed blob is empty")?;
+        match tag {
+            CODEC_STORED => Ok(rest.to_vec()),
+            CODEC_ZSTD => {
+                let (len, payload) = read_len(rest)?;
+                Ok(zstd::bulk::decompress(payload, len)?)
+            }
+            CODEC_ZSTD_DICT => {
+                let (dict_id, rest) = read_dict_id(rest)?;
+                let (len, payload) = read_len(rest)?;
+                let dict = self
+                    .options
+                    .dictionary_table
+                    .get(&dict_id)
+                    .or_else(|| match &self.options.dictionary {
+                        Some((id, dict)) if *id == dict_id => Some(dict),
+                        _ => None,
+                    })
+                    .with_context(|| {
+                        format!(
+                            "blob was compressed with dictionary id {} which is not configured \
+                             (retired without keeping it in dictionary_table?)",
+                            dict_id
+                        )
+                    })?;
+                let mut decoder = zstd::bulk::Decompressor::with_dictionary(dict)
+                    .context("failed to initialize zstd dictionary decompressor")?;
+                Ok(decoder.decompress(payload, len)?)
+            }
+            other => Err(Error::msg(format!("unknown compression codec tag {}", other))),
+        }
+    }
+}
+
+fn read_dict_id(data: &[u8]) -> Result<(u32, &[u8]), Error> {
+    anyhow::ensure!(data.len() >= 4, "compressed blob truncated (dictionary id)");
+    let (id_bytes, rest) = data.split_at(4);
+    let id = u32::from_be_bytes(id_bytes.try_into().unwrap());
+    Ok((id, rest))
+}
+
+fn read_len(data: &[u8]) -> Result<(usize, &[u8]), Error> {
+    anyhow::ensure!(data.len() >= 8, "compressed blob truncated (length)");
+    let (len_bytes, rest) = data.split_at(8);
+    let len = u64::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
+    Ok((len, rest))
+}
+
+#[async_trait]
+impl<T: Blobstore + Clone> Blobstore for CompressedBlob<T> {
+    async fn get(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+    ) -> Result<Option<BlobstoreGetData>, Error> {
+        match self.inner.get(ctx, key).await? {
+            Some(data) => {
+                let raw = self.decode(data.as_raw_bytes())?;
+                Ok(Some(BlobstoreGetData::from_bytes(BlobstoreBytes::from_bytes(raw))))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<(), Error> {
+        let encoded = self.encode(value.as_bytes())?;
+        self.inner
+            .put(ctx, key, BlobstoreBytes::from_bytes(encoded))
+            .await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps + Clone> BlobstorePutOps for CompressedBlob<T> {
+    async fn put_explicit(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus, Error> {
+        let encoded = self.encode(value.as_bytes())?;
+        self.inner
+            .put_explicit(ctx, key, BlobstoreBytes::from_bytes(encoded), put_behaviour)
+            .await
+    }
+
+    async fn put_with_status(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus, Error> {
+        self.put_explicit(ctx, key, value, self.inner.put_behaviour())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOAD: &[u8] = b"this payload is long enough to clear min_size and get compressed";
+
+    #[test]
+    fn zstd_dict_round_trips_through_the_active_dictionary() {
+        let dict = vec![1u8; 64];
+        let options = CompressionOptions {
+            min_size: 8,
+            dictionary: Some((7, dict)),
+            ..Default::default()
+        };
+        let blob = CompressedBlob::new((), options);
+
+        let encoded = blob.encode(PAYLOAD).expect("encode");
+        assert_eq!(encoded[0], CODEC_ZSTD_DICT);
+        let decoded = blob.decode(&encoded).expect("decode");
+        assert_eq!(decoded, PAYLOAD);
+    }
+
+    #[test]
+    fn zstd_dict_survives_rotation_via_dictionary_table() {
+        let old_dict = vec![1u8; 64];
+        let new_dict = vec![2u8; 64];
+
+        // Encode against the "old" dictionary before it's retired.
+        let writer = CompressedBlob::new(
+            (),
+            CompressionOptions {
+                min_size: 8,
+                dictionary: Some((1, old_dict.clone())),
+                ..Default::default()
+            },
+        );
+        let encoded = writer.encode(PAYLOAD).expect("encode");
+
+        // Rotate: the active dictionary is now id 2, but id 1 is kept around
+        // in the table so blobs written against it remain decodable.
+        let mut dictionary_table = HashMap::new();
+        dictionary_table.insert(1, old_dict);
+        let reader = CompressedBlob::new(
+            (),
+            CompressionOptions {
+                min_size: 8,
+                dictionary: Some((2, new_dict)),
+                dictionary_table,
+            },
+        );
+        let decoded = reader.decode(&encoded).expect("decode against retired dict");
+        assert_eq!(decoded, PAYLOAD);
+    }
+
+    #[test]
+    fn zstd_dict_with_unknown_id_is_a_decode_error() {
+        let dict = vec![1u8; 64];
+        let writer = CompressedBlob::new(
+            (),
+            CompressionOptions {
+                min_size: 8,
+                dictionary: Some((1, dict)),
+                ..Default::default()
+            },
+        );
+        let encoded = writer.encode(PAYLOAD).expect("encode");
+
+        let reader = CompressedBlob::new((), CompressionOptions::default());
+        let err = reader.decode(&encoded).expect_err("dictionary id 1 is unknown");
+        assert!(err.to_string().contains("dictionary id 1"));
+    }
+}
@@ -0,0 +1,278 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A read-through on-disk cache that fronts a remote blobstore with a
+//! bounded-size `Fileblob` directory, evicted by an approximate LRU. This
+//! complements `CachelibBlobstoreOptions`'s in-memory cache for processes
+//! that want their cache to survive a restart and want to cut down on
+//! egress from the remote store.
+
+#![deny(warnings)]
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use blobstore::Blobstore;
+use blobstore::BlobstoreGetData;
+use blobstore::BlobstorePutOps;
+use blobstore::BlobstoreWithLink;
+use blobstore::OverwriteStatus;
+use blobstore::PutBehaviour;
+use context::CoreContext;
+use fileblob::Fileblob;
+use mononoke_types::BlobstoreBytes;
+
+/// Tracks approximate last-access order and total size so we can evict
+/// without a full directory scan on every operation.
+#[derive(Default)]
+struct LruState {
+    sizes: HashMap<String, u64>,
+    order: Vec<String>,
+    total_bytes: u64,
+}
+
+impl LruState {
+    fn touch(&mut self, key: &str) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            let key = self.order.remove(pos);
+            self.order.push(key);
+        }
+    }
+
+    fn insert(&mut self, key: String, size: u64) {
+        if let Some(old_size) = self.sizes.insert(key.clone(), size) {
+            self.total_bytes = self.total_bytes.saturating_sub(old_size);
+        }
+        self.total_bytes += size;
+        self.touch(&key);
+        if !self.order.contains(&key) {
+            self.order.push(key);
+        }
+    }
+
+    fn evict_to_fit(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total_bytes > max_bytes {
+            if self.order.is_empty() {
+                break;
+            }
+            let oldest = self.order.remove(0);
+            if let Some(size) = self.sizes.remove(&oldest) {
+                self.total_bytes = self.total_bytes.saturating_sub(size);
+            }
+            evicted.push(oldest);
+        }
+        evicted
+    }
+
+    /// Rebuilds tracked sizes/order from whatever is already on disk under
+    /// `cache_path`, so a restart doesn't forget about (and therefore never
+    /// evict) files a previous process wrote. Order is approximated by file
+    /// modification time, oldest first, since we don't persist real access
+    /// order across restarts.
+    fn scan(cache_path: &Path) -> Self {
+        let mut entries: Vec<(String, u64, SystemTime)> = Vec::new();
+        if let Ok(dir) = std::fs::read_dir(cache_path) {
+            for entry in dir.flatten() {
+                let metadata = match entry.metadata() {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                };
+                if !metadata.is_file() {
+                    continue;
+                }
+                let key = entry.file_name().to_string_lossy().into_owned();
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                entries.push((key, metadata.len(), modified));
+            }
+        }
+        entries.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut state = LruState::default();
+        for (key, size, _modified) in entries {
+            state.sizes.insert(key.clone(), size);
+            state.total_bytes += size;
+            state.order.push(key);
+        }
+        state
+    }
+}
+
+/// A `Blobstore`/`BlobstorePutOps` wrapper that caches blobs on local disk,
+/// evicting the least-recently-used entries once `max_bytes` is exceeded.
+#[derive(Clone)]
+pub struct DiskCacheBlob<T> {
+    inner: T,
+    cache: Fileblob,
+    cache_path: PathBuf,
+    max_bytes: u64,
+    lru: std::sync::Arc<Mutex<LruState>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for DiskCacheBlob<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiskCacheBlob")
+            .field("inner", &self.inner)
+            .field("cache_path", &self.cache_path)
+            .field("max_bytes", &self.max_bytes)
+            .finish()
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for DiskCacheBlob<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DiskCacheBlob<{}, cache={}>", self.inner, self.cache_path.display())
+    }
+}
+
+impl<T> DiskCacheBlob<T> {
+    pub fn new(inner: T, cache_path: PathBuf, max_bytes: u64) -> Result<Self, Error> {
+        let cache = Fileblob::create(cache_path.clone(), PutBehaviour::Overwrite)?;
+        // Existing cache files from a prior process are still on disk and
+        // still count against max_bytes - account for them up front instead
+        // of starting from an empty (and therefore unenforced) LRU.
+        let lru = LruState::scan(&cache_path);
+        Ok(Self {
+            inner,
+            cache,
+            cache_path,
+            max_bytes,
+            lru: std::sync::Arc::new(Mutex::new(lru)),
+        })
+    }
+
+    async fn populate(&self, ctx: &CoreContext, key: &str, value: &BlobstoreBytes) -> Result<(), Error> {
+        self.cache
+            .put(ctx, key.to_string(), value.clone())
+            .await?;
+        let evicted = {
+            let mut lru = self.lru.lock().expect("lru lock poisoned");
+            lru.insert(key.to_string(), value.len() as u64);
+            lru.evict_to_fit(self.max_bytes)
+        };
+        for evicted_key in evicted {
+            // Best-effort: cache entries are allowed to linger if the unlink fails,
+            // since the remote store remains the source of truth.
+            let _ = self.cache.unlink(ctx, &evicted_key).await;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Blobstore + Clone> Blobstore for DiskCacheBlob<T> {
+    async fn get(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+    ) -> Result<Option<BlobstoreGetData>, Error> {
+        if let Some(cached) = self.cache.get(ctx, key).await? {
+            self.lru.lock().expect("lru lock poisoned").touch(key);
+            return Ok(Some(cached));
+        }
+
+        match self.inner.get(ctx, key).await? {
+            Some(data) => {
+                let bytes = BlobstoreBytes::from_bytes(data.as_raw_bytes().to_vec());
+                self.populate(ctx, key, &bytes).await?;
+                Ok(Some(data))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<(), Error> {
+        self.inner.put(ctx, key.clone(), value.clone()).await?;
+        self.populate(ctx, &key, &value).await
+    }
+}
+
+#[async_trait]
+impl<T: BlobstorePutOps + Clone> BlobstorePutOps for DiskCacheBlob<T> {
+    async fn put_explicit(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus, Error> {
+        let status = self
+            .inner
+            .put_explicit(ctx, key.clone(), value.clone(), put_behaviour)
+            .await?;
+        self.populate(ctx, &key, &value).await?;
+        Ok(status)
+    }
+
+    async fn put_with_status(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus, Error> {
+        self.put_explicit(ctx, key, value, self.inner.put_behaviour())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write as _;
+
+    use super::*;
+
+    #[test]
+    fn scan_accounts_for_files_left_by_a_previous_process() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        fs::File::create(dir.path().join("key-a"))
+            .unwrap()
+            .write_all(b"hello")
+            .unwrap();
+        fs::File::create(dir.path().join("key-b"))
+            .unwrap()
+            .write_all(b"worldwide")
+            .unwrap();
+
+        let lru = LruState::scan(dir.path());
+        assert_eq!(lru.total_bytes, 5 + 9);
+        assert_eq!(lru.sizes.get("key-a"), Some(&5));
+        assert_eq!(lru.sizes.get("key-b"), Some(&9));
+        assert_eq!(lru.order.len(), 2);
+    }
+
+    #[test]
+    fn scan_of_missing_directory_is_empty() {
+        let lru = LruState::scan(Path::new("/does/not/exist"));
+        assert_eq!(lru.total_bytes, 0);
+        assert!(lru.order.is_empty());
+    }
+
+    #[test]
+    fn evict_to_fit_drops_oldest_entries_until_under_max_bytes() {
+        let mut lru = LruState::default();
+        lru.insert("a".to_string(), 10);
+        lru.insert("b".to_string(), 10);
+        lru.insert("c".to_string(), 10);
+        assert_eq!(lru.total_bytes, 30);
+
+        let evicted = lru.evict_to_fit(15);
+        assert_eq!(evicted, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(lru.total_bytes, 10);
+    }
+}
@@ -5,6 +5,8 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashMap;
+
 use anyhow::{bail, Context, Error};
 use blobstore::{
     Blobstore, BlobstorePutOps, BlobstoreWithLink, DisabledBlob, ErrorKind, PutBehaviour,
@@ -14,19 +16,24 @@ use blobstore_sync_queue::SqlBlobstoreSyncQueue;
 use cacheblob::CachelibBlobstoreOptions;
 use cached_config::ConfigStore;
 use chaosblob::{ChaosBlobstore, ChaosOptions};
+use compressedblob::{CompressedBlob, CompressionOptions};
+use diskcacheblob::DiskCacheBlob;
+use encryptedblob::EncryptedBlob;
 use fbinit::FacebookInit;
 use fileblob::Fileblob;
 use futures::future::{self, BoxFuture, FutureExt};
 use futures_watchdog::WatchdogExt;
 use logblob::LogBlob;
 use metaconfig_types::{
-    BlobConfig, BlobstoreId, DatabaseConfig, MultiplexId, MultiplexedStoreType,
+    BlobConfig, BlobstoreId, DatabaseConfig, MultiplexId, MultiplexedStoreType, ReadStrategy,
     ShardableRemoteDatabaseConfig,
 };
 use multiplexedblob::{
     MultiplexedBlobstore, ScrubAction, ScrubBlobstore, ScrubHandler, ScrubOptions,
 };
+use objectstoreblob::ObjectStoreBlob;
 use packblob::{PackBlob, PackOptions};
+use quorumblob::{QuorumBlobstore, QuorumSize};
 use readonlyblob::ReadOnlyBlobstore;
 use scuba_ext::MononokeScubaSampleBuilder;
 use slog::Logger;
@@ -375,6 +382,22 @@ async fn make_blobstore_with_link<'a>(
         Files { .. } => make_files_blobstore(blobconfig, blobstore_options)
             .await
             .map(|store| Arc::new(store) as Arc<dyn BlobstoreWithLink>),
+        Encrypted {
+            key_source,
+            blobconfig,
+        } => {
+            let store = make_blobstore_with_link(
+                fb,
+                *blobconfig,
+                readonly_storage,
+                blobstore_options,
+                logger,
+                config_store,
+            )
+            .watched(logger)
+            .await?;
+            Ok(Arc::new(EncryptedBlob::new(store, key_source)) as Arc<dyn BlobstoreWithLink>)
+        }
         _ => bail!("Not a physical blobstore"),
     }
 }
@@ -452,6 +475,26 @@ fn make_blobstore_put_ops<'a>(
                     unimplemented!("This is implemented only for fbcode_build")
                 }
             }
+            ObjectStore {
+                provider,
+                bucket,
+                prefix,
+                endpoint,
+                region,
+                credentials,
+                num_concurrent_operations,
+            } => ObjectStoreBlob::new(
+                provider,
+                bucket,
+                prefix,
+                endpoint,
+                region,
+                credentials,
+                blobstore_options.put_behaviour,
+                num_concurrent_operations,
+            )
+            .context(ErrorKind::StateOpen)
+            .map(|store| Arc::new(store) as Arc<dyn BlobstorePutOps>)?,
 
             // Special case
             Disabled => {
@@ -466,6 +509,7 @@ fn make_blobstore_put_ops<'a>(
                 blobstores,
                 minimum_successful_writes,
                 queue_db,
+                read_strategy,
             } => {
                 has_components = true;
                 make_blobstore_multiplexed(
@@ -476,6 +520,7 @@ fn make_blobstore_put_ops<'a>(
                     scuba_sample_rate,
                     blobstores,
                     minimum_successful_writes,
+                    read_strategy,
                     mysql_options,
                     readonly_storage,
                     blobstore_options,
@@ -522,6 +567,76 @@ fn make_blobstore_put_ops<'a>(
             .watched(logger)
             .await
             .map(|store| Arc::new(store) as Arc<dyn BlobstorePutOps>)?,
+            Encrypted {
+                key_source,
+                blobconfig,
+            } => {
+                let store = make_blobstore_put_ops(
+                    fb,
+                    *blobconfig,
+                    mysql_options,
+                    readonly_storage,
+                    &blobstore_options,
+                    logger,
+                    config_store,
+                    scrub_handler,
+                )
+                .watched(logger)
+                .await?;
+                Arc::new(EncryptedBlob::new(store, key_source)) as Arc<dyn BlobstorePutOps>
+            }
+            Compressed {
+                level,
+                dictionary,
+                min_size,
+                blobconfig,
+            } => {
+                let store = make_blobstore_put_ops(
+                    fb,
+                    *blobconfig,
+                    mysql_options,
+                    readonly_storage,
+                    &blobstore_options,
+                    logger,
+                    config_store,
+                    scrub_handler,
+                )
+                .watched(logger)
+                .await?;
+                let options = CompressionOptions {
+                    level,
+                    min_size,
+                    dictionary,
+                    // `BlobConfig::Compressed` has no field yet to configure
+                    // retired dictionaries from, so decode-time dictionary
+                    // rotation isn't reachable through config today - only
+                    // whatever `dictionary` is currently active can be read
+                    // back. Wire a `retired_dictionaries` config field
+                    // through here once one exists.
+                    dictionary_table: HashMap::new(),
+                };
+                Arc::new(CompressedBlob::new(store, options)) as Arc<dyn BlobstorePutOps>
+            }
+            DiskCache {
+                cache_path,
+                max_bytes,
+                blobconfig,
+            } => {
+                let store = make_blobstore_put_ops(
+                    fb,
+                    *blobconfig,
+                    mysql_options,
+                    readonly_storage,
+                    &blobstore_options,
+                    logger,
+                    config_store,
+                    scrub_handler,
+                )
+                .watched(logger)
+                .await?;
+                Arc::new(DiskCacheBlob::new(store, cache_path, max_bytes)?)
+                    as Arc<dyn BlobstorePutOps>
+            }
         };
 
         let store = if readonly_storage.0 {
@@ -565,6 +680,7 @@ async fn make_blobstore_multiplexed<'a>(
     scuba_sample_rate: NonZeroU64,
     inner_config: Vec<(BlobstoreId, MultiplexedStoreType, BlobConfig)>,
     minimum_successful_writes: NonZeroUsize,
+    read_strategy: ReadStrategy,
     mysql_options: &'a MysqlOptions,
     readonly_storage: ReadOnlyStorage,
     blobstore_options: &'a BlobstoreOptions,
@@ -640,13 +756,15 @@ async fn make_blobstore_multiplexed<'a>(
         (normal_components, write_mostly_components)
     };
 
-    let blobstore = match &blobstore_options.scrub_options {
-        Some(scrub_options) => Arc::new(ScrubBlobstore::new(
+    let queue = Arc::new(queue);
+
+    let blobstore = match (&blobstore_options.scrub_options, read_strategy) {
+        (Some(scrub_options), _) => Arc::new(ScrubBlobstore::new(
             multiplex_id,
             normal_components,
             write_mostly_components,
             minimum_successful_writes,
-            Arc::new(queue),
+            queue,
             scuba_table.map_or(MononokeScubaSampleBuilder::with_discard(), |table| {
                 MononokeScubaSampleBuilder::new(fb, &table)
             }),
@@ -654,12 +772,25 @@ async fn make_blobstore_multiplexed<'a>(
             scrub_options.clone(),
             scrub_handler.clone(),
         )) as Arc<dyn BlobstorePutOps>,
-        None => Arc::new(MultiplexedBlobstore::new(
+        // Quorum reads only make sense without scrub: scrub already reads from
+        // every component and repairs divergence itself.
+        (None, ReadStrategy::Quorum(quorum)) => Arc::new(QuorumBlobstore::new(
+            multiplex_id,
+            normal_components,
+            write_mostly_components,
+            QuorumSize(quorum),
+            minimum_successful_writes,
+            queue,
+            scuba_table.map_or(MononokeScubaSampleBuilder::with_discard(), |table| {
+                MononokeScubaSampleBuilder::new(fb, &table)
+            }),
+        )) as Arc<dyn BlobstorePutOps>,
+        (None, ReadStrategy::Any) => Arc::new(MultiplexedBlobstore::new(
             multiplex_id,
             normal_components,
             write_mostly_components,
             minimum_successful_writes,
-            Arc::new(queue),
+            queue,
             scuba_table.map_or(MononokeScubaSampleBuilder::with_discard(), |table| {
                 MononokeScubaSampleBuilder::new(fb, &table)
             }),
@@ -0,0 +1,304 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A multiplexed blobstore variant that issues reads to several components
+//! in parallel and requires a configurable number of them to agree before
+//! returning, so silent corruption of a single component can be detected at
+//! read time rather than waiting for a full scrub pass.
+
+#![deny(warnings)]
+
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use blobstore::Blobstore;
+use blobstore::BlobstoreGetData;
+use blobstore::BlobstorePutOps;
+use blobstore::OverwriteStatus;
+use blobstore::PutBehaviour;
+use blobstore_sync_queue::BlobstoreSyncQueue;
+use blobstore_sync_queue::BlobstoreSyncQueueEntry;
+use blobstore_sync_queue::SqlBlobstoreSyncQueue;
+use context::CoreContext;
+use futures::future::join_all;
+use metaconfig_types::BlobstoreId;
+use metaconfig_types::MultiplexId;
+use mononoke_types::BlobstoreBytes;
+use mononoke_types::Timestamp;
+use scuba_ext::MononokeScubaSampleBuilder;
+
+/// Number of components that must agree on a `get` result before it is
+/// returned to the caller, capped at the number of `normal` components.
+#[derive(Clone, Copy, Debug)]
+pub struct QuorumSize(pub NonZeroUsize);
+
+/// A `BlobstorePutOps` that fronts a set of components: `get` reads from
+/// every `normal` component in parallel and requires at least
+/// `quorum_size` of them to agree, while `put` writes to every component
+/// (`normal` and `write_mostly`, matching `MultiplexedBlobstore`).
+#[derive(Clone)]
+pub struct QuorumBlobstore {
+    multiplex_id: MultiplexId,
+    normal_components: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
+    write_mostly_components: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
+    quorum_size: QuorumSize,
+    minimum_successful_writes: NonZeroUsize,
+    queue: Arc<SqlBlobstoreSyncQueue>,
+    scuba: MononokeScubaSampleBuilder,
+}
+
+impl fmt::Debug for QuorumBlobstore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuorumBlobstore")
+            .field("multiplex_id", &self.multiplex_id)
+            .field("quorum_size", &self.quorum_size.0)
+            .field("normal_component_count", &self.normal_components.len())
+            .field(
+                "write_mostly_component_count",
+                &self.write_mostly_components.len(),
+            )
+            .finish()
+    }
+}
+
+impl fmt::Display for QuorumBlobstore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "QuorumBlobstore[{}: quorum {} of {}]",
+            self.multiplex_id,
+            self.quorum_size.0,
+            self.normal_components.len()
+        )
+    }
+}
+
+impl QuorumBlobstore {
+    pub fn new(
+        multiplex_id: MultiplexId,
+        normal_components: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
+        write_mostly_components: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
+        quorum_size: QuorumSize,
+        minimum_successful_writes: NonZeroUsize,
+        queue: Arc<SqlBlobstoreSyncQueue>,
+        scuba: MononokeScubaSampleBuilder,
+    ) -> Self {
+        Self {
+            multiplex_id,
+            normal_components,
+            write_mostly_components,
+            quorum_size,
+            minimum_successful_writes,
+            queue,
+            scuba,
+        }
+    }
+
+    fn all_components(&self) -> impl Iterator<Item = &(BlobstoreId, Arc<dyn BlobstorePutOps>)> {
+        self.normal_components
+            .iter()
+            .chain(self.write_mostly_components.iter())
+    }
+
+    async fn quorum_get(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+    ) -> Result<Option<BlobstoreGetData>, Error> {
+        // Read every normal component, not just as many as the quorum
+        // requires - otherwise we can never notice a minority of them
+        // diverging, which defeats the point of a quorum read.
+        let results = join_all(self.normal_components.iter().map(|(id, store)| {
+            let store = store.clone();
+            async move { (*id, store.get(ctx, key).await) }
+        }))
+        .await;
+        let queried = results.len();
+
+        // Group responses by their value, keeping `None` (key not found)
+        // distinct from `Some(data)` where `data` happens to be empty -
+        // those are different facts about the world and must not be
+        // conflated when looking for agreement.
+        let mut groups: HashMap<Option<Vec<u8>>, (Option<BlobstoreGetData>, Vec<BlobstoreId>)> =
+            HashMap::new();
+        let mut errored = Vec::new();
+        for (id, result) in results {
+            match result {
+                Ok(Some(data)) => {
+                    let raw = Some(data.as_raw_bytes().to_vec());
+                    groups.entry(raw).or_insert((Some(data), Vec::new())).1.push(id);
+                }
+                Ok(None) => {
+                    groups.entry(None).or_insert((None, Vec::new())).1.push(id);
+                }
+                Err(_) => errored.push(id),
+            }
+        }
+
+        let quorum = self.quorum_size.0.get().min(self.normal_components.len().max(1));
+        let winner = groups
+            .into_iter()
+            .max_by_key(|(_, (_, ids))| ids.len())
+            .filter(|(_, (_, ids))| ids.len() >= quorum);
+
+        match winner {
+            Some((_, (data, agreeing))) => {
+                // Anything queried but not in the winning group - whether it
+                // errored or simply disagreed - is a divergence worth
+                // repairing, even though we still have enough agreement to
+                // answer the caller.
+                if agreeing.len() != queried {
+                    self.flag_divergence(ctx, key).await;
+                }
+                Ok(data)
+            }
+            None => {
+                self.flag_divergence(ctx, key).await;
+                Err(Error::msg(format!(
+                    "quorum of {} not reached for key {} across {} components",
+                    quorum, key, queried
+                )))
+            }
+        }
+    }
+
+    async fn flag_divergence(&self, ctx: &CoreContext, key: &str) {
+        let mut scuba = self.scuba.clone();
+        scuba.add("key", key);
+        scuba.add("multiplex_id", self.multiplex_id.to_string());
+        scuba.add("reason", "quorum_divergence");
+        scuba.log();
+
+        // Record a sync-queue entry against every component so the regular
+        // repair pass picks this key up and reconciles it.
+        let entries: Vec<_> = self
+            .all_components()
+            .map(|(id, _)| {
+                BlobstoreSyncQueueEntry::new(
+                    key.to_string(),
+                    *id,
+                    self.multiplex_id,
+                    Timestamp::now(),
+                    None,
+                )
+            })
+            .collect();
+        let _ = self.queue.add_many(ctx, entries).await;
+    }
+}
+
+#[async_trait]
+impl Blobstore for QuorumBlobstore {
+    async fn get(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+    ) -> Result<Option<BlobstoreGetData>, Error> {
+        self.quorum_get(ctx, key).await
+    }
+
+    async fn put(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<(), Error> {
+        self.put_with_status(ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for QuorumBlobstore {
+    async fn put_explicit(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus, Error> {
+        let total = self.normal_components.len() + self.write_mostly_components.len();
+        let puts = join_all(self.all_components().map(|(_, store)| {
+            let store = store.clone();
+            let key = key.clone();
+            let value = value.clone();
+            async move { store.put_explicit(ctx, key, value, put_behaviour).await }
+        }))
+        .await;
+
+        let successes = puts.iter().filter(|r| r.is_ok()).count();
+        if successes < self.minimum_successful_writes.get() {
+            return Err(Error::msg(format!(
+                "only {} of {} writes succeeded, need at least {}",
+                successes,
+                total,
+                self.minimum_successful_writes.get()
+            )));
+        }
+        Ok(puts
+            .into_iter()
+            .find_map(Result::ok)
+            .unwrap_or(OverwriteStatus::NotChecked))
+    }
+
+    async fn put_with_status(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus, Error> {
+        self.put_explicit(ctx, key, value, PutBehaviour::Overwrite)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // These exercise the pure grouping/voting logic in `quorum_get` without
+    // standing up real component blobstores, by reimplementing just the
+    // grouping step against hand-built results - the same data shapes that
+    // drove the divergence-detection bugs fixed above.
+    fn vote(
+        results: Vec<(u64, Option<Vec<u8>>)>,
+        quorum: usize,
+    ) -> Option<(Option<Vec<u8>>, usize)> {
+        use std::collections::HashMap;
+        let mut groups: HashMap<Option<Vec<u8>>, Vec<u64>> = HashMap::new();
+        for (id, value) in results {
+            groups.entry(value).or_default().push(id);
+        }
+        groups
+            .into_iter()
+            .max_by_key(|(_, ids)| ids.len())
+            .filter(|(_, ids)| ids.len() >= quorum)
+            .map(|(value, ids)| (value, ids.len()))
+    }
+
+    #[test]
+    fn quorum_reached_with_majority_agreement() {
+        let results = vec![
+            (1, Some(b"a".to_vec())),
+            (2, Some(b"a".to_vec())),
+            (3, Some(b"corrupt".to_vec())),
+        ];
+        let winner = vote(results, 2).expect("quorum of 2 should be reached");
+        assert_eq!(winner, (Some(b"a".to_vec()), 2));
+    }
+
+    #[test]
+    fn none_and_empty_blob_are_distinct_groups() {
+        let results = vec![(1, None), (2, Some(Vec::new())), (3, None)];
+        // `None` (missing) has 2 votes, `Some(vec![])` (stored empty blob)
+        // has 1 - they must not be merged into a single group of 3.
+        let winner = vote(results, 3);
+        assert_eq!(winner, None, "2 missing + 1 empty blob must not reach quorum 3");
+    }
+}
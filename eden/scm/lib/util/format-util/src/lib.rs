@@ -0,0 +1,44 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Utilities for working with on-disk object formats (currently Git's).
+
+mod git_sha1;
+pub mod object;
+pub mod pack;
+pub mod packet_line;
+pub mod ssh_sig;
+
+pub use git_sha1::git_object_identity;
+pub use git_sha1::git_object_serialize_write;
+pub use git_sha1::git_sha1_deserialize;
+pub use git_sha1::git_sha1_deserialize_read;
+pub use git_sha1::git_sha1_serialize;
+pub use git_sha1::git_sha1_serialize_write;
+pub use git_sha1::HashAlgo;
+
+/// An `io::Write` sink that only counts the bytes written to it, used to
+/// size a buffer before actually writing into it.
+#[derive(Default)]
+pub(crate) struct ByteCount(usize);
+
+impl std::io::Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl From<ByteCount> for usize {
+    fn from(count: ByteCount) -> usize {
+        count.0
+    }
+}
@@ -0,0 +1,455 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Reading and writing Git packfiles (`.pack` files, and the packs the
+//! smart transport streams over the wire). A pack is a 12-byte header
+//! followed by a sequence of (possibly delta-encoded) zlib-deflated
+//! objects, and a trailing SHA-1 checksum of everything before it.
+
+use std::io::Read;
+use std::io::Write;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context as _;
+use anyhow::Result;
+use flate2::bufread::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha1::Digest as _;
+use sha1::Sha1;
+
+const PACK_MAGIC: &[u8; 4] = b"PACK";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ObjectType {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+    OfsDelta,
+    RefDelta,
+}
+
+impl ObjectType {
+    fn from_type_bits(bits: u8) -> Result<Self> {
+        Ok(match bits {
+            1 => ObjectType::Commit,
+            2 => ObjectType::Tree,
+            3 => ObjectType::Blob,
+            4 => ObjectType::Tag,
+            6 => ObjectType::OfsDelta,
+            7 => ObjectType::RefDelta,
+            other => bail!("invalid pack object type {}", other),
+        })
+    }
+
+    fn to_type_bits(self) -> u8 {
+        match self {
+            ObjectType::Commit => 1,
+            ObjectType::Tree => 2,
+            ObjectType::Blob => 3,
+            ObjectType::Tag => 4,
+            ObjectType::OfsDelta => 6,
+            ObjectType::RefDelta => 7,
+        }
+    }
+
+    /// The `"<kind>"` string used in the loose-object framing, for the
+    /// non-delta types that are actual Git objects.
+    pub fn kind_str(self) -> Option<&'static str> {
+        match self {
+            ObjectType::Commit => Some("commit"),
+            ObjectType::Tree => Some("tree"),
+            ObjectType::Blob => Some("blob"),
+            ObjectType::Tag => Some("tag"),
+            ObjectType::OfsDelta | ObjectType::RefDelta => None,
+        }
+    }
+}
+
+/// The base a delta entry applies against, before it has been resolved to
+/// an actual object.
+#[derive(Clone, Debug)]
+pub enum DeltaBase {
+    /// `OBJ_OFS_DELTA`: base is `offset` bytes before this entry's start.
+    Offset(u64),
+    /// `OBJ_REF_DELTA`: base is identified by its oid.
+    Ref([u8; 20]),
+}
+
+/// One entry read out of a packfile, before delta resolution.
+#[derive(Clone, Debug)]
+pub struct RawEntry {
+    pub offset: u64,
+    pub object_type: ObjectType,
+    pub delta_base: Option<DeltaBase>,
+    /// The inflated payload: either a whole object body, or a delta
+    /// instruction stream to apply against `delta_base`.
+    pub data: Vec<u8>,
+}
+
+/// A packfile's 12-byte header: magic, version, object count.
+#[derive(Copy, Clone, Debug)]
+pub struct PackHeader {
+    pub version: u32,
+    pub object_count: u32,
+}
+
+/// Reads packfile entries out of any `io::Read`, tracking enough state to
+/// resolve `OBJ_OFS_DELTA`/`OBJ_REF_DELTA` entries against objects it has
+/// already seen earlier in the stream.
+pub struct PackReader<R> {
+    // Buffered so the zlib decoder consumes exactly one entry's compressed
+    // bytes and leaves the next entry in place.
+    reader: std::io::BufReader<R>,
+    pub header: PackHeader,
+    next_offset: u64,
+    // Earlier entries, keyed by their starting offset, kept around so
+    // OFS_DELTA entries later in the pack can find their base.
+    by_offset: std::collections::HashMap<u64, RawEntry>,
+}
+
+impl<R: Read> PackReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        ensure!(&magic == PACK_MAGIC, "not a packfile (bad magic)");
+
+        let version = read_u32_be(&mut reader)?;
+        let object_count = read_u32_be(&mut reader)?;
+
+        Ok(Self {
+            reader: std::io::BufReader::new(reader),
+            header: PackHeader {
+                version,
+                object_count,
+            },
+            next_offset: 12,
+            by_offset: std::collections::HashMap::new(),
+        })
+    }
+
+    /// Reads the next entry, or `None` once `header.object_count` entries
+    /// have been consumed.
+    pub fn read_entry(&mut self) -> Result<Option<RawEntry>> {
+        if self.by_offset.len() as u32 >= self.header.object_count {
+            return Ok(None);
+        }
+
+        let start_offset = self.next_offset;
+        let (object_type, size, header_len) = read_entry_header(&mut self.reader)?;
+
+        let delta_base = match object_type {
+            ObjectType::OfsDelta => {
+                let (delta_offset, len) = read_offset_delta(&mut self.reader)?;
+                self.next_offset += len as u64;
+                ensure!(
+                    delta_offset <= start_offset,
+                    "OFS_DELTA base offset points before the start of the pack"
+                );
+                Some(DeltaBase::Offset(start_offset - delta_offset))
+            }
+            ObjectType::RefDelta => {
+                let mut oid = [0u8; 20];
+                self.reader.read_exact(&mut oid)?;
+                self.next_offset += 20;
+                Some(DeltaBase::Ref(oid))
+            }
+            _ => None,
+        };
+
+        self.next_offset += header_len as u64;
+
+        let mut decoder = ZlibDecoder::new(&mut self.reader);
+        let mut data = Vec::with_capacity(size);
+        decoder.read_to_end(&mut data)?;
+        self.next_offset += decoder.total_in();
+        ensure!(
+            data.len() == size,
+            "pack entry at offset {} inflated to {} bytes, expected {} (corrupt or truncated pack)",
+            start_offset,
+            data.len(),
+            size
+        );
+
+        let entry = RawEntry {
+            offset: start_offset,
+            object_type,
+            delta_base,
+            data,
+        };
+        self.by_offset.insert(start_offset, entry.clone());
+        Ok(Some(entry))
+    }
+
+    /// Fully resolves `entry` to its raw object bytes (applying delta
+    /// instructions against earlier entries as needed) and returns the raw
+    /// object body, without loose-object framing, alongside its kind.
+    pub fn resolve(&self, entry: &RawEntry) -> Result<(Vec<u8>, &'static str)> {
+        let (raw, object_type) = self.resolve_raw(entry)?;
+        let kind = object_type
+            .kind_str()
+            .context("resolved entry is still a delta type")?;
+        Ok((raw, kind))
+    }
+
+    fn resolve_raw(&self, entry: &RawEntry) -> Result<(Vec<u8>, ObjectType)> {
+        match &entry.delta_base {
+            None => Ok((entry.data.clone(), entry.object_type)),
+            Some(DeltaBase::Offset(base_offset)) => {
+                let base = self
+                    .by_offset
+                    .get(base_offset)
+                    .context("OFS_DELTA base not found earlier in pack")?;
+                let (base_raw, base_type) = self.resolve_raw(base)?;
+                Ok((apply_delta(&base_raw, &entry.data)?, base_type))
+            }
+            Some(DeltaBase::Ref(oid)) => {
+                let base = self
+                    .by_offset
+                    .values()
+                    .find(|e| object_id(e, self).map(|id| &id == oid).unwrap_or(false))
+                    .context("REF_DELTA base not found earlier in pack")?;
+                let (base_raw, base_type) = self.resolve_raw(base)?;
+                Ok((apply_delta(&base_raw, &entry.data)?, base_type))
+            }
+        }
+    }
+}
+
+/// The object id (SHA-1) of a resolved, non-delta entry's contents.
+fn object_id<R>(entry: &RawEntry, reader: &PackReader<R>) -> Option<[u8; 20]> {
+    let (raw, object_type) = reader.resolve_raw(entry).ok()?;
+    let kind = object_type.kind_str()?;
+    let framed = crate::git_sha1_serialize(&raw, kind);
+    let digest = Sha1::new().chain_update(&framed).finalize();
+    let mut oid = [0u8; 20];
+    oid.copy_from_slice(&digest);
+    Some(oid)
+}
+
+// Reads the variable-length (type, size) entry header: first byte holds a
+// 3-bit type and the low 4 size bits, continued in 7-bit groups while the
+// high bit is set.
+fn read_entry_header(reader: &mut impl Read) -> Result<(ObjectType, usize, usize)> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    let mut len = 1;
+    let object_type = ObjectType::from_type_bits((byte[0] >> 4) & 0b111)?;
+    let mut size = (byte[0] & 0b1111) as usize;
+    let mut shift = 4;
+    while byte[0] & 0x80 != 0 {
+        reader.read_exact(&mut byte)?;
+        len += 1;
+        size |= ((byte[0] & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+    Ok((object_type, size, len))
+}
+
+// Reads an OFS_DELTA negative offset: big-endian base-128 with the "+1"
+// carry convention used by Git (each continuation byte adds 1 before being
+// shifted in, so offsets don't collide).
+fn read_offset_delta(reader: &mut impl Read) -> Result<(u64, usize)> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    let mut len = 1;
+    let mut value = (byte[0] & 0x7f) as u64;
+    while byte[0] & 0x80 != 0 {
+        reader.read_exact(&mut byte)?;
+        len += 1;
+        value = ((value + 1) << 7) | (byte[0] & 0x7f) as u64;
+    }
+    Ok((value, len))
+}
+
+/// Applies a Git delta instruction stream to `base`, producing the target
+/// object's bytes. Each instruction is either a copy (high bit set: offset
+/// and size, each optionally present in the following bytes) or an insert
+/// (high bit clear: the byte itself is a literal length, followed by that
+/// many literal bytes).
+fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let (_base_size, consumed) = read_varint(delta, pos)?;
+    pos += consumed;
+    let (target_size, consumed) = read_varint(delta, pos)?;
+    pos += consumed;
+
+    let mut out = Vec::with_capacity(target_size);
+    while pos < delta.len() {
+        let opcode = delta[pos];
+        pos += 1;
+        if opcode & 0x80 != 0 {
+            let mut offset: u64 = 0;
+            let mut size: u64 = 0;
+            for bit in 0..4 {
+                if opcode & (1 << bit) != 0 {
+                    offset |= (delta[pos] as u64) << (8 * bit);
+                    pos += 1;
+                }
+            }
+            for bit in 0..3 {
+                if opcode & (1 << (4 + bit)) != 0 {
+                    size |= (delta[pos] as u64) << (8 * bit);
+                    pos += 1;
+                }
+            }
+            let size = if size == 0 { 0x10000 } else { size };
+            let (offset, size) = (offset as usize, size as usize);
+            ensure!(offset + size <= base.len(), "delta copy out of bounds");
+            out.extend_from_slice(&base[offset..offset + size]);
+        } else {
+            let size = opcode as usize;
+            ensure!(pos + size <= delta.len(), "delta insert out of bounds");
+            out.extend_from_slice(&delta[pos..pos + size]);
+            pos += size;
+        }
+    }
+    ensure!(out.len() == target_size, "delta target size mismatch");
+    Ok(out)
+}
+
+// The base/target sizes at the front of a delta stream: 7-bit groups,
+// little-endian this time (unlike the OFS_DELTA offset encoding above).
+fn read_varint(data: &[u8], mut pos: usize) -> Result<(usize, usize)> {
+    let start = pos;
+    let mut value: usize = 0;
+    let mut shift = 0;
+    loop {
+        ensure!(pos < data.len(), "truncated delta varint");
+        let byte = data[pos];
+        pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok((value, pos - start))
+}
+
+fn read_u32_be(reader: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+/// Writes a packfile from a sequence of whole (non-delta) objects, emitting
+/// the 12-byte header, each zlib-deflated entry, and the trailing pack
+/// checksum.
+pub struct PackWriter<W> {
+    writer: W,
+    hasher: Sha1,
+    object_count: u32,
+    written: u32,
+}
+
+impl<W: Write> PackWriter<W> {
+    pub fn new(mut writer: W, object_count: u32) -> Result<Self> {
+        let mut hasher = Sha1::new();
+        let mut header = Vec::with_capacity(12);
+        header.extend_from_slice(PACK_MAGIC);
+        header.extend_from_slice(&2u32.to_be_bytes());
+        header.extend_from_slice(&object_count.to_be_bytes());
+        writer.write_all(&header)?;
+        hasher.update(&header);
+        Ok(Self {
+            writer,
+            hasher,
+            object_count,
+            written: 0,
+        })
+    }
+
+    /// Appends one whole object (no delta encoding) to the pack.
+    pub fn write_object(&mut self, object_type: ObjectType, data: &[u8]) -> Result<()> {
+        ensure!(
+            self.written < self.object_count,
+            "wrote more objects than the declared object_count"
+        );
+        let header = encode_entry_header(object_type, data.len());
+        self.writer.write_all(&header)?;
+        self.hasher.update(&header);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+        self.writer.write_all(&compressed)?;
+        self.hasher.update(&compressed);
+
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Finishes the pack, writing the trailing SHA-1 checksum, and returns it.
+    pub fn finish(mut self) -> Result<[u8; 20]> {
+        ensure!(
+            self.written == self.object_count,
+            "wrote fewer objects than the declared object_count"
+        );
+        let digest = self.hasher.finalize();
+        let mut checksum = [0u8; 20];
+        checksum.copy_from_slice(&digest);
+        self.writer.write_all(&checksum)?;
+        Ok(checksum)
+    }
+}
+
+fn encode_entry_header(object_type: ObjectType, size: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut first = (object_type.to_type_bits() << 4) | ((size & 0b1111) as u8);
+    let mut size = size >> 4;
+    while size > 0 {
+        out.push(first | 0x80);
+        first = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    out.push(first);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_single_object_pack(object_type: ObjectType, data: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut writer = PackWriter::new(&mut buf, 1).expect("new writer");
+        writer.write_object(object_type, data).expect("write object");
+        writer.finish().expect("finish");
+        buf
+    }
+
+    #[test]
+    fn read_entry_round_trips_a_whole_object() {
+        let packed = write_single_object_pack(ObjectType::Blob, b"hello world");
+        let mut reader = PackReader::new(packed.as_slice()).expect("new reader");
+        let entry = reader
+            .read_entry()
+            .expect("read entry")
+            .expect("entry present");
+        assert_eq!(entry.object_type, ObjectType::Blob);
+        assert_eq!(entry.data, b"hello world");
+        assert!(reader.read_entry().expect("read entry").is_none());
+    }
+
+    #[test]
+    fn read_entry_rejects_a_size_that_does_not_match_the_inflated_payload() {
+        let mut packed = write_single_object_pack(ObjectType::Blob, b"hello world");
+        // The entry header's low 4 size bits live in the low nibble of the
+        // 13th byte (right after the 12-byte pack header); change the
+        // declared size without touching the compressed payload so the
+        // inflated length no longer matches what the header promises.
+        packed[12] = (packed[12] & 0xf0) | ((packed[12] & 0x0f) ^ 0x0f);
+        let mut reader = PackReader::new(packed.as_slice()).expect("new reader");
+        reader
+            .read_entry()
+            .expect_err("mismatched size should be rejected");
+    }
+}
+
@@ -0,0 +1,183 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Git's pkt-line framing, used by the smart transport (`git fetch`,
+//! `ls-refs`, etc). Each packet is a 4-byte lowercase-hex length prefix
+//! covering itself and the payload, with `0000` and `0001` reserved as the
+//! flush-pkt and delimiter-pkt markers.
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context as _;
+use anyhow::Result;
+
+/// Maximum payload (excluding the 4-byte length prefix) allowed in a single
+/// pkt-line data packet.
+pub const MAX_PAYLOAD_LEN: usize = 65516;
+const MAX_PKT_LEN: usize = MAX_PAYLOAD_LEN + 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Packet {
+    Data(Vec<u8>),
+    Flush,
+    Delim,
+}
+
+/// Writes a data packet: `"<4-hex-length>" || data`. If `as_line` is set, a
+/// trailing `"\n"` is appended to `data` first, matching the line-packet
+/// convention most smart-protocol commands use.
+pub fn encode_data(out: &mut dyn Write, data: &[u8], as_line: bool) -> Result<()> {
+    let mut payload = data.to_vec();
+    if as_line {
+        payload.push(b'\n');
+    }
+    ensure!(
+        payload.len() <= MAX_PAYLOAD_LEN,
+        "pkt-line payload of {} bytes exceeds the {} byte cap",
+        payload.len(),
+        MAX_PAYLOAD_LEN
+    );
+    let len = payload.len() + 4;
+    out.write_all(format!("{:04x}", len).as_bytes())?;
+    out.write_all(&payload)?;
+    Ok(())
+}
+
+/// Writes a flush-pkt (`"0000"`), signaling the end of a response.
+pub fn encode_flush(out: &mut dyn Write) -> Result<()> {
+    out.write_all(b"0000")?;
+    Ok(())
+}
+
+/// Writes a delimiter-pkt (`"0001"`), used to separate sections within a
+/// single response (e.g. command args from the rest of a v2 request).
+pub fn encode_delim(out: &mut dyn Write) -> Result<()> {
+    out.write_all(b"0001")?;
+    Ok(())
+}
+
+/// Reads a single pkt-line packet from `reader`.
+pub fn decode(reader: &mut dyn Read) -> Result<Packet> {
+    let mut len_hex = [0u8; 4];
+    reader
+        .read_exact(&mut len_hex)
+        .context("failed to read pkt-line length prefix")?;
+    let len_str = std::str::from_utf8(&len_hex).context("pkt-line length prefix is not ASCII")?;
+    let len = usize::from_str_radix(len_str, 16).context("pkt-line length prefix is not hex")?;
+
+    match len {
+        0 => Ok(Packet::Flush),
+        1 => Ok(Packet::Delim),
+        len if len < 4 => bail!("invalid pkt-line length {} (must be 0, 1, or >= 4)", len),
+        len if len > MAX_PKT_LEN => bail!(
+            "pkt-line length {} exceeds the {} byte cap",
+            len,
+            MAX_PKT_LEN
+        ),
+        len => {
+            let mut payload = vec![0u8; len - 4];
+            reader
+                .read_exact(&mut payload)
+                .context("failed to read pkt-line payload")?;
+            Ok(Packet::Data(payload))
+        }
+    }
+}
+
+/// Reads packets from `reader` until a flush-pkt, yielding each one via
+/// `callback`. The flush-pkt itself is not passed to `callback`.
+pub fn decode_until_flush(
+    reader: &mut dyn Read,
+    mut callback: impl FnMut(Packet) -> io::Result<()>,
+) -> Result<()> {
+    loop {
+        match decode(reader)? {
+            Packet::Flush => return Ok(()),
+            packet => callback(packet)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_then_decode_data_packet_round_trips() {
+        let mut buf = Vec::new();
+        encode_data(&mut buf, b"want deadbeef", false).unwrap();
+        assert_eq!(&buf, b"0011want deadbeef");
+
+        let packet = decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(packet, Packet::Data(b"want deadbeef".to_vec()));
+    }
+
+    #[test]
+    fn encode_data_as_line_appends_newline() {
+        let mut buf = Vec::new();
+        encode_data(&mut buf, b"hello", true).unwrap();
+        let packet = decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(packet, Packet::Data(b"hello\n".to_vec()));
+    }
+
+    #[test]
+    fn flush_and_delim_pkts_round_trip() {
+        let mut flush = Vec::new();
+        encode_flush(&mut flush).unwrap();
+        assert_eq!(flush, b"0000");
+        assert_eq!(decode(&mut flush.as_slice()).unwrap(), Packet::Flush);
+
+        let mut delim = Vec::new();
+        encode_delim(&mut delim).unwrap();
+        assert_eq!(delim, b"0001");
+        assert_eq!(decode(&mut delim.as_slice()).unwrap(), Packet::Delim);
+    }
+
+    #[test]
+    fn encode_data_rejects_oversized_payload() {
+        let mut buf = Vec::new();
+        let oversized = vec![0u8; MAX_PAYLOAD_LEN + 1];
+        encode_data(&mut buf, &oversized, false).expect_err("payload exceeds the cap");
+    }
+
+    #[test]
+    fn decode_rejects_length_between_two_and_three() {
+        // 2 and 3 are neither a reserved marker nor long enough to cover the
+        // 4-byte length prefix itself.
+        let mut reader = b"0002".as_slice();
+        decode(&mut reader).expect_err("length 2 is invalid");
+    }
+
+    #[test]
+    fn decode_until_flush_yields_every_packet_before_the_flush() {
+        let mut stream = Vec::new();
+        encode_data(&mut stream, b"one", false).unwrap();
+        encode_data(&mut stream, b"two", false).unwrap();
+        encode_flush(&mut stream).unwrap();
+        // Anything after the flush-pkt must not be consumed by this call.
+        encode_data(&mut stream, b"three", false).unwrap();
+
+        let mut seen = Vec::new();
+        decode_until_flush(&mut stream.as_slice(), |packet| {
+            seen.push(packet);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            seen,
+            vec![
+                Packet::Data(b"one".to_vec()),
+                Packet::Data(b"two".to_vec()),
+            ]
+        );
+    }
+}